@@ -0,0 +1,155 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+
+//compact vector-clock token: one (node_id, counter) entry per writer that has touched a
+//document, so `Simba::update_with_token` can tell a concurrent write from a stale retry --
+//something the single `version` integer can't do
+pub type CausalityToken = Vec<(u64, u64)>;
+
+pub enum CausalityOrdering {
+    Equal,
+    Dominates,
+    Dominated,
+    Conflict,
+}
+
+fn counter_of(token: &CausalityToken, node_id: u64) -> u64 {
+    token
+        .iter()
+        .find(|(n, _)| *n == node_id)
+        .map(|(_, c)| *c)
+        .unwrap_or(0)
+}
+
+//standard vector-clock comparison: `a` dominates `b` if it is ahead or equal on every node
+//and strictly ahead on at least one; if neither side dominates the other, they conflict
+pub fn compare(a: &CausalityToken, b: &CausalityToken) -> CausalityOrdering {
+    let mut nodes: Vec<u64> = a.iter().chain(b.iter()).map(|(n, _)| *n).collect();
+    nodes.sort();
+    nodes.dedup();
+
+    let mut a_ge_b = true;
+    let mut b_ge_a = true;
+    for node in nodes {
+        let (ac, bc) = (counter_of(a, node), counter_of(b, node));
+        if ac < bc {
+            a_ge_b = false;
+        }
+        if bc < ac {
+            b_ge_a = false;
+        }
+    }
+
+    match (a_ge_b, b_ge_a) {
+        (true, true) => CausalityOrdering::Equal,
+        (true, false) => CausalityOrdering::Dominates,
+        (false, true) => CausalityOrdering::Dominated,
+        (false, false) => CausalityOrdering::Conflict,
+    }
+}
+
+//bumps this node's own counter, producing the token to store alongside a new write
+pub fn advance(token: &CausalityToken, node_id: u64) -> CausalityToken {
+    let mut next = token.clone();
+    match next.iter_mut().find(|(n, _)| *n == node_id) {
+        Some((_, c)) => *c += 1,
+        None => next.push((node_id, 1)),
+    }
+    next
+}
+
+//component-wise max of two tokens, used to fold a client-supplied token and the currently
+//stored one back into a single clock before advancing it
+pub fn merge(a: &CausalityToken, b: &CausalityToken) -> CausalityToken {
+    let mut merged = a.clone();
+    for (node, counter) in b {
+        match merged.iter_mut().find(|(n, _)| n == node) {
+            Some((_, c)) if *c < *counter => *c = *counter,
+            Some(_) => {}
+            None => merged.push((*node, *counter)),
+        }
+    }
+    merged.sort();
+    merged
+}
+
+pub fn encode(token: &CausalityToken) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(token.len() * 16);
+    for (node, counter) in token {
+        buf.extend_from_slice(&node.to_be_bytes());
+        buf.extend_from_slice(&counter.to_be_bytes());
+    }
+    buf
+}
+
+pub fn decode(bytes: &[u8]) -> CausalityToken {
+    bytes
+        .chunks_exact(16)
+        .map(|c| {
+            let node = u64::from_be_bytes(c[0..8].try_into().unwrap());
+            let counter = u64::from_be_bytes(c[8..16].try_into().unwrap());
+            (node, counter)
+        })
+        .collect()
+}
+
+pub const CAUSALITY_KEY_PREFIX: &[u8] = b"ct/";
+
+//side keyspace holding each document's causality token, keyed by its iid so it travels
+//alongside the document without needing a new protobuf field on `Document` itself
+pub fn causality_key(iid: &[u8]) -> Vec<u8> {
+    let mut key = CAUSALITY_KEY_PREFIX.to_vec();
+    key.extend_from_slice(iid);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_tokens_are_equal() {
+        assert!(matches!(compare(&vec![], &vec![]), CausalityOrdering::Equal));
+    }
+
+    #[test]
+    fn advancing_dominates_the_original() {
+        let a = vec![(1, 1)];
+        let b = advance(&a, 1);
+        assert!(matches!(compare(&b, &a), CausalityOrdering::Dominates));
+        assert!(matches!(compare(&a, &b), CausalityOrdering::Dominated));
+    }
+
+    #[test]
+    fn divergent_advances_conflict() {
+        let base = vec![(1, 1), (2, 1)];
+        let a = advance(&base, 1);
+        let b = advance(&base, 2);
+        assert!(matches!(compare(&a, &b), CausalityOrdering::Conflict));
+        assert!(matches!(compare(&b, &a), CausalityOrdering::Conflict));
+    }
+
+    #[test]
+    fn merge_takes_the_component_wise_max() {
+        let a = vec![(1, 3), (2, 1)];
+        let b = vec![(1, 1), (2, 5), (3, 2)];
+        assert_eq!(merge(&a, &b), vec![(1, 3), (2, 5), (3, 2)]);
+    }
+
+    #[test]
+    fn encode_decode_round_trips() {
+        let token = vec![(1, 3), (7, 42)];
+        assert_eq!(decode(&encode(&token)), token);
+    }
+}
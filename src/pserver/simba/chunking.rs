@@ -0,0 +1,188 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+
+//content-defined chunking for large `doc.source` payloads, so repeated/near-duplicate
+//uploads only pay for the chunks that actually changed
+use sha2::{Digest, Sha256};
+
+const WINDOW: usize = 48;
+const PRIME: u64 = 153191;
+const MODULUS: u64 = 1 << 61;
+
+pub const CHUNK_KEY_PREFIX: &str = "chunk/";
+
+pub struct ChunkingConfig {
+    pub threshold: usize,
+    pub avg_size: usize,
+    pub min_size: usize,
+    pub max_size: usize,
+}
+
+impl ChunkingConfig {
+    pub fn new(threshold: usize, avg_size: usize, min_size: usize, max_size: usize) -> Self {
+        ChunkingConfig {
+            threshold,
+            avg_size,
+            min_size,
+            max_size,
+        }
+    }
+
+    pub fn default() -> Self {
+        //1MiB threshold, ~64KiB average chunks, clamped between 16KiB and 256KiB
+        ChunkingConfig::new(1 << 20, 1 << 16, 1 << 14, 1 << 18)
+    }
+
+    fn mask(&self) -> u64 {
+        (self.avg_size as u64).next_power_of_two() - 1
+    }
+}
+
+//splits `source` on content-defined boundaries using a Rabin-style rolling hash over a
+//sliding window: a boundary is cut whenever the low bits of the hash are all zero, which
+//keeps identical runs of bytes chunked identically regardless of surrounding inserts/deletes
+pub fn chunk_source(source: &[u8], cfg: &ChunkingConfig) -> Vec<&[u8]> {
+    if source.len() <= cfg.min_size {
+        return vec![source];
+    }
+
+    let mask = cfg.mask();
+    let mut out = Vec::new();
+    let mut start = 0usize;
+    let mut hash: u64 = 0;
+    let mut pow: u64 = 1;
+    for _ in 0..WINDOW {
+        pow = pow.wrapping_mul(PRIME) % MODULUS;
+    }
+
+    let mut i = 0usize;
+    while i < source.len() {
+        let len = i - start + 1;
+        hash = (hash.wrapping_mul(PRIME).wrapping_add(source[i] as u64)) % MODULUS;
+        if len > WINDOW {
+            let out_byte = source[i - WINDOW] as u64;
+            hash = (hash + MODULUS - (out_byte.wrapping_mul(pow) % MODULUS)) % MODULUS;
+        }
+
+        let at_max = len >= cfg.max_size;
+        let at_boundary = len >= cfg.min_size && hash & mask == 0;
+        if at_max || at_boundary {
+            out.push(&source[start..=i]);
+            start = i + 1;
+            hash = 0;
+        }
+        i += 1;
+    }
+
+    if start < source.len() {
+        out.push(&source[start..]);
+    }
+
+    out
+}
+
+pub fn chunk_hash(chunk: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(chunk);
+    hex::encode(hasher.finalize())
+}
+
+pub fn chunk_key(hash: &str) -> Vec<u8> {
+    format!("{}{}", CHUNK_KEY_PREFIX, hash).into_bytes()
+}
+
+//the serialized form `doc.source` takes once it has been chunked: a leading sentinel byte
+//(never valid as the first byte of a JSON source) followed by the ordered, comma-joined
+//list of content-addressed hashes that the reader reassembles by concatenation
+const CHUNKED_MARKER: u8 = 0;
+
+pub fn encode_chunk_list(hashes: &[String]) -> Vec<u8> {
+    let mut out = vec![CHUNKED_MARKER];
+    out.extend_from_slice(hashes.join(",").as_bytes());
+    out
+}
+
+pub fn is_chunked(source: &[u8]) -> bool {
+    source.first() == Some(&CHUNKED_MARKER)
+}
+
+pub fn decode_chunk_list(encoded: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(&encoded[1..])
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> ChunkingConfig {
+        ChunkingConfig::new(0, 64, 16, 256)
+    }
+
+    #[test]
+    fn small_source_is_not_split() {
+        let cfg = ChunkingConfig::new(0, 64, 16, 256);
+        let source = vec![1u8; 8];
+        assert_eq!(chunk_source(&source, &cfg), vec![source.as_slice()]);
+    }
+
+    #[test]
+    fn chunks_reassemble_to_the_original_source() {
+        let cfg = cfg();
+        let source: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+        let chunks = chunk_source(&source, &cfg);
+        let reassembled: Vec<u8> = chunks.iter().flat_map(|c| c.to_vec()).collect();
+        assert_eq!(reassembled, source);
+    }
+
+    #[test]
+    fn no_chunk_exceeds_max_size() {
+        let cfg = cfg();
+        let source: Vec<u8> = (0..5000).map(|i| (i * 7 % 256) as u8).collect();
+        for chunk in chunk_source(&source, &cfg) {
+            assert!(chunk.len() <= cfg.max_size);
+        }
+    }
+
+    #[test]
+    fn identical_runs_cut_the_same_chunk_boundaries() {
+        let cfg = cfg();
+        let mut a: Vec<u8> = (0..1000).map(|i| (i * 31 % 256) as u8).collect();
+        let mut b = a.clone();
+        a.extend_from_slice(b"tail-a");
+        b.extend_from_slice(b"tail-b-longer");
+
+        let chunks_a = chunk_source(&a, &cfg);
+        let chunks_b = chunk_source(&b, &cfg);
+        //everything but the final (differing) chunk should be identical, proving the content-
+        //defined boundaries don't shift just because bytes were appended at the end
+        assert_eq!(&chunks_a[..chunks_a.len() - 1], &chunks_b[..chunks_a.len() - 1]);
+    }
+
+    #[test]
+    fn chunk_list_round_trips_through_encode_decode() {
+        let hashes = vec![chunk_hash(b"one"), chunk_hash(b"two")];
+        let encoded = encode_chunk_list(&hashes);
+        assert!(is_chunked(&encoded));
+        assert_eq!(decode_chunk_list(&encoded), hashes);
+    }
+
+    #[test]
+    fn plain_json_source_is_never_mistaken_for_chunked() {
+        assert!(!is_chunked(br#"{"a":1}"#));
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+
+//abstracts the handful of operations `Simba` needs from its KV engine, so the engine can be
+//swapped per deployment (or faked out in tests) instead of being hardwired to RocksDB
+use crate::pserver::simba::engine::engine::BaseEngine;
+use crate::pserver::simba::engine::lmdb_backend::LmdbBackend;
+use crate::pserver::simba::engine::mem_backend::MemBackend;
+use crate::pserver::simba::engine::rocksdb::RocksDB;
+use crate::util::{config::*, error::*};
+use std::sync::Arc;
+
+pub trait KvBackend: Send + Sync {
+    fn get(&self, key: &[u8]) -> ASResult<Option<Vec<u8>>>;
+    fn write(&self, key: &[u8], value: &[u8]) -> ASResult<()>;
+    fn delete(&self, key: &[u8]) -> ASResult<()>;
+    fn flush(&self) -> ASResult<()>;
+    //estimated number of live keys, used by `Simba::count` alongside the Tantivy index count.
+    //keys starting with any of `exclude_prefixes` (the chunk store or causality-token
+    //keyspaces that share this backend with documents) are not counted, for the same reason
+    //`scan`'s `exclude_prefixes` keeps them out of a document page
+    fn count(&self, exclude_prefixes: &[&[u8]]) -> ASResult<u64>;
+    fn get_sn(&self) -> u64;
+    fn write_sn(&self, sn: u64) -> ASResult<()>;
+    fn release(&self);
+
+    //returns up to `limit` (key, value) pairs in key order (or reverse key order), bounded by
+    //`start`/`end`; backs `Simba::scan`. Keys starting with any of `exclude_prefixes` (e.g. the
+    //chunk store or causality-token keyspaces that share this backend with documents) are
+    //skipped before they count against `limit`, so a page never comes back short because the
+    //row budget was spent on keys the caller was never going to see
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+        reverse: bool,
+        exclude_prefixes: &[&[u8]],
+    ) -> ASResult<Vec<(Vec<u8>, Vec<u8>)>>;
+}
+
+fn excluded(key: &[u8], exclude_prefixes: &[&[u8]]) -> bool {
+    exclude_prefixes.iter().any(|p| key.starts_with(p))
+}
+
+impl KvBackend for RocksDB {
+    fn get(&self, key: &[u8]) -> ASResult<Option<Vec<u8>>> {
+        match self.db.get(key) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(err_box(format!("get key has err:{}", e.to_string()))),
+        }
+    }
+
+    fn write(&self, key: &[u8], value: &[u8]) -> ASResult<()> {
+        RocksDB::write(self, &key.to_vec(), &value.to_vec())
+    }
+
+    fn delete(&self, key: &[u8]) -> ASResult<()> {
+        RocksDB::delete(self, &key.to_vec())
+    }
+
+    fn flush(&self) -> ASResult<()> {
+        RocksDB::flush(self)
+    }
+
+    fn count(&self, exclude_prefixes: &[&[u8]]) -> ASResult<u64> {
+        if exclude_prefixes.is_empty() {
+            return RocksDB::count(self);
+        }
+        let mut n = 0u64;
+        for item in self.db.iterator(rocksdb::IteratorMode::Start) {
+            let (k, _) =
+                item.map_err(|e| err_box(format!("count iterator has err:{}", e.to_string())))?;
+            if !excluded(k.as_ref(), exclude_prefixes) {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    fn get_sn(&self) -> u64 {
+        RocksDB::get_sn(self)
+    }
+
+    fn write_sn(&self, sn: u64) -> ASResult<()> {
+        RocksDB::write_sn(self, sn)
+    }
+
+    fn release(&self) {
+        RocksDB::release(self)
+    }
+
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+        reverse: bool,
+        exclude_prefixes: &[&[u8]],
+    ) -> ASResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        use rocksdb::{Direction, IteratorMode};
+
+        //`start` is the inclusive lower bound and `end` the exclusive upper bound in both
+        //directions; a reverse scan walks down from `end`
+        let mode = match (start, end, reverse) {
+            (Some(s), _, false) => IteratorMode::From(s, Direction::Forward),
+            (None, _, false) => IteratorMode::Start,
+            (_, Some(e), true) => IteratorMode::From(e, Direction::Reverse),
+            (_, None, true) => IteratorMode::End,
+        };
+
+        let mut out = Vec::new();
+        for item in self.db.iterator(mode) {
+            let (k, v) =
+                item.map_err(|e| err_box(format!("scan iterator has err:{}", e.to_string())))?;
+            if reverse {
+                if end.map_or(false, |end| k.as_ref() >= end) {
+                    continue;
+                }
+                if start.map_or(false, |start| k.as_ref() < start) {
+                    break;
+                }
+            } else if end.map_or(false, |end| k.as_ref() >= end) {
+                break;
+            }
+            if excluded(k.as_ref(), exclude_prefixes) {
+                continue;
+            }
+            out.push((k.to_vec(), v.to_vec()));
+            if out.len() >= limit {
+                break;
+            }
+        }
+        Ok(out)
+    }
+}
+
+//picks the KV backend for a partition from `ps.kv_backend` ("rocksdb" by default), so tests
+//and workloads that don't need a real RocksDB build can run against lmdb/memory instead
+pub fn open_kv_backend(conf: &Arc<Config>, base: Arc<BaseEngine>) -> ASResult<Box<dyn KvBackend>> {
+    match conf.ps.kv_backend.as_deref().unwrap_or("rocksdb") {
+        "lmdb" => Ok(Box::new(LmdbBackend::new(base)?)),
+        "memory" => Ok(Box::new(MemBackend::new(base)?)),
+        _ => Ok(Box::new(RocksDB::new(base)?)),
+    }
+}
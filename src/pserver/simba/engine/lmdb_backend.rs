@@ -0,0 +1,191 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+
+//LMDB-backed `KvBackend`, for workloads that prefer a memory-mapped single-writer store
+//over RocksDB's LSM tree
+use crate::pserver::simba::engine::engine::BaseEngine;
+use crate::pserver::simba::engine::kv_backend::KvBackend;
+use crate::util::error::*;
+use lmdb::{Cursor, Database, Environment, Transaction, WriteFlags};
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
+use std::sync::Arc;
+
+const SN_KEY: &[u8] = b"__lmdb_sn__";
+
+pub struct LmdbBackend {
+    _base: Arc<BaseEngine>,
+    env: Environment,
+    db: Database,
+    sn: AtomicU64,
+}
+
+impl LmdbBackend {
+    pub fn new(base: Arc<BaseEngine>) -> ASResult<LmdbBackend> {
+        let path = format!(
+            "{}/{}/{}/lmdb",
+            base.conf.ps.data_path.as_deref().unwrap_or("data"),
+            base.collection.id,
+            base.partition.id,
+        );
+        fs::create_dir_all(&path)?;
+
+        let env = Environment::new()
+            .set_max_dbs(1)
+            .open(std::path::Path::new(&path))
+            .map_err(|e| err_box(format!("open lmdb env has err:{}", e.to_string())))?;
+        let db = env
+            .create_db(None, lmdb::DatabaseFlags::empty())
+            .map_err(|e| err_box(format!("open lmdb db has err:{}", e.to_string())))?;
+
+        let sn = {
+            let txn = env
+                .begin_ro_txn()
+                .map_err(|e| err_box(format!("begin lmdb txn has err:{}", e.to_string())))?;
+            match txn.get(db, &SN_KEY) {
+                Ok(v) => u64::from_be_bytes(v.try_into().unwrap_or([0; 8])),
+                Err(lmdb::Error::NotFound) => 0,
+                Err(e) => return Err(err_box(format!("read lmdb sn has err:{}", e.to_string()))),
+            }
+        };
+
+        Ok(LmdbBackend {
+            _base: base,
+            env,
+            db,
+            sn: AtomicU64::new(sn),
+        })
+    }
+}
+
+impl KvBackend for LmdbBackend {
+    fn get(&self, key: &[u8]) -> ASResult<Option<Vec<u8>>> {
+        let txn = self
+            .env
+            .begin_ro_txn()
+            .map_err(|e| err_box(format!("begin lmdb txn has err:{}", e.to_string())))?;
+        match txn.get(self.db, &key) {
+            Ok(v) => Ok(Some(v.to_vec())),
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(e) => Err(err_box(format!("get key has err:{}", e.to_string()))),
+        }
+    }
+
+    fn write(&self, key: &[u8], value: &[u8]) -> ASResult<()> {
+        let mut txn = self
+            .env
+            .begin_rw_txn()
+            .map_err(|e| err_box(format!("begin lmdb txn has err:{}", e.to_string())))?;
+        txn.put(self.db, &key, &value, WriteFlags::empty())
+            .map_err(|e| err_box(format!("write key has err:{}", e.to_string())))?;
+        txn.commit()
+            .map_err(|e| err_box(format!("commit lmdb txn has err:{}", e.to_string())))
+    }
+
+    fn delete(&self, key: &[u8]) -> ASResult<()> {
+        let mut txn = self
+            .env
+            .begin_rw_txn()
+            .map_err(|e| err_box(format!("begin lmdb txn has err:{}", e.to_string())))?;
+        match txn.del(self.db, &key, None) {
+            Ok(_) | Err(lmdb::Error::NotFound) => {}
+            Err(e) => return Err(err_box(format!("delete key has err:{}", e.to_string()))),
+        }
+        txn.commit()
+            .map_err(|e| err_box(format!("commit lmdb txn has err:{}", e.to_string())))
+    }
+
+    fn flush(&self) -> ASResult<()> {
+        self.env
+            .sync(true)
+            .map_err(|e| err_box(format!("sync lmdb env has err:{}", e.to_string())))
+    }
+
+    fn count(&self, exclude_prefixes: &[&[u8]]) -> ASResult<u64> {
+        let txn = self
+            .env
+            .begin_ro_txn()
+            .map_err(|e| err_box(format!("begin lmdb txn has err:{}", e.to_string())))?;
+        let mut cursor = txn
+            .open_ro_cursor(self.db)
+            .map_err(|e| err_box(format!("open lmdb cursor has err:{}", e.to_string())))?;
+        let mut n = 0u64;
+        for item in cursor.iter() {
+            let (k, _) =
+                item.map_err(|e| err_box(format!("count cursor has err:{}", e.to_string())))?;
+            if !exclude_prefixes.iter().any(|p| k.starts_with(p)) {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    fn get_sn(&self) -> u64 {
+        self.sn.load(SeqCst)
+    }
+
+    fn write_sn(&self, sn: u64) -> ASResult<()> {
+        self.write(SN_KEY, &sn.to_be_bytes())?;
+        self.sn.store(sn, SeqCst);
+        Ok(())
+    }
+
+    fn release(&self) {}
+
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+        reverse: bool,
+        exclude_prefixes: &[&[u8]],
+    ) -> ASResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let txn = self
+            .env
+            .begin_ro_txn()
+            .map_err(|e| err_box(format!("begin lmdb txn has err:{}", e.to_string())))?;
+        let mut cursor = txn
+            .open_ro_cursor(self.db)
+            .map_err(|e| err_box(format!("open lmdb cursor has err:{}", e.to_string())))?;
+
+        let iter: Box<dyn Iterator<Item = lmdb::Result<(&[u8], &[u8])>>> = match start {
+            Some(s) => Box::new(cursor.iter_from(s)),
+            None => Box::new(cursor.iter_start()),
+        };
+
+        let mut out = Vec::new();
+        for item in iter {
+            let (k, v) = item.map_err(|e| err_box(format!("scan cursor has err:{}", e.to_string())))?;
+            if let Some(end) = end {
+                if k >= end {
+                    break;
+                }
+            }
+            if exclude_prefixes.iter().any(|p| k.starts_with(p)) {
+                continue;
+            }
+            out.push((k.to_vec(), v.to_vec()));
+            if !reverse && out.len() >= limit {
+                break;
+            }
+        }
+        //lmdb cursors only walk forward, so a reverse scan collects the whole [start, end)
+        //range and keeps its last `limit` rows, highest key first
+        if reverse {
+            out.reverse();
+            out.truncate(limit);
+        }
+        Ok(out)
+    }
+}
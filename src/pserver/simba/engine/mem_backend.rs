@@ -0,0 +1,116 @@
+// Copyright 2020 The Chubao Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied. See the License for the specific language governing
+// permissions and limitations under the License.
+
+//in-memory `KvBackend`, mainly for unit tests and throwaway partitions that don't want to
+//pay for a RocksDB/LMDB instance on disk
+use crate::pserver::simba::engine::engine::BaseEngine;
+use crate::pserver::simba::engine::kv_backend::KvBackend;
+use crate::util::error::*;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
+use std::sync::{Arc, RwLock};
+
+pub struct MemBackend {
+    _base: Arc<BaseEngine>,
+    map: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+    sn: AtomicU64,
+}
+
+impl MemBackend {
+    pub fn new(base: Arc<BaseEngine>) -> ASResult<MemBackend> {
+        Ok(MemBackend {
+            _base: base,
+            map: RwLock::new(BTreeMap::new()),
+            sn: AtomicU64::new(0),
+        })
+    }
+}
+
+impl KvBackend for MemBackend {
+    fn get(&self, key: &[u8]) -> ASResult<Option<Vec<u8>>> {
+        Ok(self.map.read().unwrap().get(key).cloned())
+    }
+
+    fn write(&self, key: &[u8], value: &[u8]) -> ASResult<()> {
+        self.map
+            .write()
+            .unwrap()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> ASResult<()> {
+        self.map.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn flush(&self) -> ASResult<()> {
+        Ok(())
+    }
+
+    fn count(&self, exclude_prefixes: &[&[u8]]) -> ASResult<u64> {
+        Ok(self
+            .map
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|k| !exclude_prefixes.iter().any(|p| k.starts_with(p)))
+            .count() as u64)
+    }
+
+    fn get_sn(&self) -> u64 {
+        self.sn.load(SeqCst)
+    }
+
+    fn write_sn(&self, sn: u64) -> ASResult<()> {
+        self.sn.store(sn, SeqCst);
+        Ok(())
+    }
+
+    fn release(&self) {
+        self.map.write().unwrap().clear();
+    }
+
+    fn scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+        reverse: bool,
+        exclude_prefixes: &[&[u8]],
+    ) -> ASResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+
+        let lower = match start {
+            Some(s) => Included(s.to_vec()),
+            None => Unbounded,
+        };
+        let upper = match end {
+            Some(e) => Excluded(e.to_vec()),
+            None => Unbounded,
+        };
+
+        let map = self.map.read().unwrap();
+        let mut items: Vec<(Vec<u8>, Vec<u8>)> = map
+            .range((lower, upper))
+            .filter(|(k, _)| !exclude_prefixes.iter().any(|p| k.starts_with(p)))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if reverse {
+            items.reverse();
+        }
+        items.truncate(limit);
+        Ok(items)
+    }
+}
@@ -11,10 +11,18 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
 // implied. See the License for the specific language governing
 // permissions and limitations under the License.
+use crate::pserver::simba::causality::{
+    advance, causality_key, compare, decode as decode_token, encode as encode_token,
+    merge as merge_tokens, CausalityOrdering, CausalityToken, CAUSALITY_KEY_PREFIX,
+};
+use crate::pserver::simba::chunking::{
+    chunk_hash, chunk_key, chunk_source, decode_chunk_list, encode_chunk_list, is_chunked,
+    ChunkingConfig, CHUNK_KEY_PREFIX,
+};
 use crate::pserver::simba::engine::{
     engine::{BaseEngine, Engine},
+    kv_backend::{open_kv_backend, KvBackend},
     raft::*,
-    rocksdb::RocksDB,
     tantivy::Tantivy,
 };
 use crate::pserver::simba::latch::Latch;
@@ -32,6 +40,7 @@ use log::{error, info, warn};
 use prost::Message;
 use serde_json::Value;
 use std::cmp;
+use std::collections::HashMap;
 use std::marker::Send;
 use std::sync::{
     atomic::{AtomicBool, Ordering::SeqCst},
@@ -40,6 +49,15 @@ use std::sync::{
 
 use jimraft::raft::LogReader;
 
+//a page returned by `Simba::scan`/`Simba::scan_prefix`: the reassembled documents found, in
+//key order (or reverse key order), plus a continuation cursor in iid-key form -- a forward
+//scan pages on with `start` set to it (it already lies just past the last row), a reverse
+//scan with `end` set to it; `next` is `None` only when the page read no rows
+pub struct ScanPage {
+    pub docs: Vec<Vec<u8>>,
+    pub next: Option<Vec<u8>>,
+}
+
 pub struct Simba {
     pub conf: Arc<Config>,
     _collection: Arc<Collection>,
@@ -48,9 +66,11 @@ pub struct Simba {
     pub started: AtomicBool,
     writable: AtomicBool,
     latch: Latch,
+    chunking: ChunkingConfig,
+    committer: Committer,
     max_sn: RwLock<u64>,
     //engins
-    pub rocksdb: Option<RocksDB>,
+    pub rocksdb: Option<Box<dyn KvBackend>>,
     pub tantivy: Option<Tantivy>,
     pub raft: Option<RaftEngine>,
     pub base_engine: Arc<BaseEngine>,
@@ -72,6 +92,7 @@ impl Simba {
             collection: collection.clone(),
             partition: partition.clone(),
         });
+        let (committer_tx, committer_rx) = tokio::sync::mpsc::unbounded_channel();
         let simba: Arc<RwLock<Simba>> = Arc::new(RwLock::new(Simba {
             rocksdb: None,
             tantivy: None,
@@ -83,6 +104,13 @@ impl Simba {
             started: AtomicBool::new(true),
             writable: AtomicBool::new(false),
             latch: Latch::new(50000),
+            chunking: ChunkingConfig::new(
+                conf.ps.chunk_threshold_bytes.unwrap_or(1 << 20),
+                conf.ps.chunk_avg_size_bytes.unwrap_or(1 << 16),
+                conf.ps.chunk_min_size_bytes.unwrap_or(1 << 14),
+                conf.ps.chunk_max_size_bytes.unwrap_or(1 << 18),
+            ),
+            committer: Committer { tx: committer_tx },
             base_engine: base.clone(),
             server_id: server_id,
             start_latch: latch.clone(),
@@ -113,15 +141,45 @@ impl Simba {
             );
         });
 
+        let simba_commit = simba.clone();
+        let max_batch = conf.ps.max_batch.unwrap_or(200);
+        let max_delay_ms = conf.ps.max_delay_ms.unwrap_or(5);
+        tokio::spawn(async move {
+            if readonly {
+                return;
+            }
+            run_committer(simba_commit, committer_rx, max_batch, max_delay_ms).await;
+        });
+
         Ok(simba.clone())
     }
 
     pub fn get(&self, id: &str, sort_key: &str) -> ASResult<Vec<u8>> {
-        self.get_by_iid(id_coding(id, sort_key).as_ref())
+        let raw = self.get_by_iid(id_coding(id, sort_key).as_ref())?;
+        self._reassemble_chunks(raw)
+    }
+
+    //chunked documents store only an ordered list of chunk hashes in `doc.source`; stitch the
+    //real payload back together before handing the document to a caller
+    fn _reassemble_chunks(&self, raw: Vec<u8>) -> ASResult<Vec<u8>> {
+        let mut doc: Document = Message::decode(prost::bytes::Bytes::from(raw.clone()))?;
+        if !is_chunked(&doc.source) {
+            return Ok(raw);
+        }
+
+        let mut source = Vec::new();
+        for hash in decode_chunk_list(&doc.source) {
+            source.extend_from_slice(&self.get_by_iid(&chunk_key(&hash))?);
+        }
+        doc.source = source;
+
+        let mut buf = Vec::new();
+        doc.encode(&mut buf)?;
+        Ok(buf)
     }
 
     fn get_by_iid(&self, iid: &Vec<u8>) -> ASResult<Vec<u8>> {
-        match self.rocksdb.as_ref().unwrap().db.get(iid) {
+        match self.rocksdb.as_ref().unwrap().get(iid) {
             Ok(ov) => match ov {
                 Some(v) => Ok(v),
                 None => Err(err_code_str_box(NOT_FOUND, "not found!")),
@@ -132,13 +190,104 @@ impl Simba {
 
     //it use 1.estimate of rocksdb  2.index of u64
     pub fn count(&self) -> ASResult<(u64, u64)> {
-        let estimate_rocksdb = self.rocksdb.count()?;
+        //exclude the chunk store and causality-token keyspaces so the rocksdb estimate
+        //approximates document count, the same way `scan` already excludes them from a page
+        let estimate_rocksdb = self
+            .rocksdb
+            .as_ref()
+            .unwrap()
+            .count(&[CHUNK_KEY_PREFIX.as_bytes(), CAUSALITY_KEY_PREFIX])?;
 
         let tantivy_count = self.tantivy.count()?;
 
         Ok((estimate_rocksdb, tantivy_count))
     }
 
+    //iterates documents by id range over the RocksDB iid keyspace in `id_coding` order,
+    //for export/reindex/pagination use cases that point `get` and full-text `search` can't
+    //serve. `start`/`end` are inclusive/exclusive id bounds; either may be omitted to scan
+    //to the beginning/end of the keyspace
+    pub fn scan(
+        &self,
+        start: Option<&str>,
+        end: Option<&str>,
+        limit: usize,
+        reverse: bool,
+    ) -> ASResult<ScanPage> {
+        self._scan_iid(
+            start.map(|s| id_coding(s, "")),
+            end.map(|s| id_coding(s, "")),
+            limit,
+            reverse,
+        )
+    }
+
+    //hierarchical-id convenience over `scan`: every document whose id starts with `prefix`,
+    //by turning it into a half-open [prefix, upper_bound) range
+    pub fn scan_prefix(&self, prefix: &str, limit: usize, reverse: bool) -> ASResult<ScanPage> {
+        let start = id_coding(prefix, "");
+        let end = prefix_upper_bound(&start);
+        self._scan_iid(Some(start), end, limit, reverse)
+    }
+
+    fn _scan_iid(
+        &self,
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        limit: usize,
+        reverse: bool,
+    ) -> ASResult<ScanPage> {
+        if !self.check_writable() {
+            return Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!"));
+        }
+
+        //the iid keyspace shares its RocksDB with the chunk store and causality tokens; exclude
+        //both inside the backend's scan so `limit` counts document rows only, instead of being
+        //spent on interleaved internal keys and coming back short (or empty) on chunked data
+        let rows = self.rocksdb.as_ref().unwrap().scan(
+            start.as_deref(),
+            end.as_deref(),
+            limit,
+            reverse,
+            &[CHUNK_KEY_PREFIX.as_bytes(), CAUSALITY_KEY_PREFIX],
+        )?;
+
+        //every backend's `scan` treats `start` as an inclusive lower bound and `end` as an
+        //exclusive upper bound in both directions. A forward cursor is the immediate successor
+        //of the last row returned, or re-issuing the scan with `start = page.next` would return
+        //that same row again; a reverse cursor is the last (lowest) row itself, used as `end`
+        let cursor = |key: &[u8]| {
+            if reverse {
+                key.to_vec()
+            } else {
+                successor_key(key)
+            }
+        };
+
+        let mut docs = Vec::with_capacity(rows.len());
+        let mut next = None;
+        for (key, value) in rows {
+            //a tombstoned/raced-out key left an empty value behind; skip it
+            if value.is_empty() {
+                next = Some(cursor(&key));
+                continue;
+            }
+
+            match self._reassemble_chunks(value) {
+                Ok(buf) => docs.push(buf),
+                Err(e) => {
+                    let e = cast_to_err(e);
+                    if e.0 != NOT_FOUND {
+                        return Err(e);
+                    }
+                }
+            }
+            next = Some(cursor(&key));
+        }
+
+        Ok(ScanPage { docs, next })
+    }
+
     pub fn search(&self, sdreq: Arc<SearchDocumentRequest>) -> SearchDocumentResponse {
         match self.tantivy.as_ref().unwrap().search(sdreq) {
             Ok(r) => r,
@@ -173,9 +322,133 @@ impl Simba {
         }
     }
 
+    //writes every request of the batch through a single raft entry and a single latch wait,
+    //instead of paying one raft round-trip per document like `write` does
+    pub fn batch_write(&self, reqs: Vec<WriteDocumentRequest>) -> ASResult<()> {
+        let mut slots: Vec<u32> = reqs
+            .iter()
+            .filter_map(|r| r.doc.as_ref().map(|d| d.slot))
+            .collect();
+        slots.sort();
+        slots.dedup();
+        //acquire every distinct slot latch up front, in order, so concurrent batches can't deadlock
+        let _locks: Vec<_> = slots.iter().map(|slot| self.latch.latch_lock(*slot)).collect();
+
+        //tracks the document each op leaves behind within this batch, so a later op touching
+        //the same id merges against what an earlier op in the same batch staged rather than
+        //against stale already-committed RocksDB state
+        let mut staged: HashMap<Vec<u8>, Option<Document>> = HashMap::new();
+        let mut ops = Vec::with_capacity(reqs.len());
+        for req in reqs {
+            ops.extend(self._prepare_batch_op(req, &mut staged)?);
+        }
+
+        self.do_batch_write(ops)
+    }
+
+    //returns every `BatchOp` this request contributes to the batch: any chunk payloads
+    //`_chunk_source` split out, plus the document's own `Put`/`Del`
+    fn _prepare_batch_op(
+        &self,
+        req: WriteDocumentRequest,
+        staged: &mut HashMap<Vec<u8>, Option<Document>>,
+    ) -> ASResult<Vec<BatchOp>> {
+        let (mut doc, write_type) = (req.doc.unwrap(), WriteType::from_i32(req.write_type));
+
+        match write_type {
+            Some(WriteType::Delete) => {
+                let iid = doc_id(&doc);
+                staged.insert(iid.clone(), None);
+                Ok(vec![BatchOp::Del(iid)])
+            }
+            Some(WriteType::Overwrite) | Some(WriteType::Create) => {
+                let iid = doc_id(&doc);
+                doc.version = 1;
+                //stage the real (unchunked) source so a later op in this batch merges
+                //against actual content rather than a chunk-hash-list marker
+                staged.insert(iid.clone(), Some(doc.clone()));
+                let mut ops = self._chunk_source(&mut doc)?;
+                let mut buf = Vec::new();
+                if let Err(error) = doc.encode(&mut buf) {
+                    return Err(error.into());
+                }
+                ops.push(BatchOp::Put(iid, buf));
+                Ok(ops)
+            }
+            Some(WriteType::Update) => {
+                let (old_version, iid) = (doc.version, doc_id(&doc));
+                let old = match staged.get(&iid) {
+                    Some(Some(staged_doc)) => staged_doc.clone(),
+                    Some(None) => return Err(err_code_str_box(NOT_FOUND, "not found!")),
+                    None => {
+                        let old = self.get(doc.id.as_str(), doc.sort_key.as_str())?;
+                        Message::decode(prost::bytes::Bytes::from(old))?
+                    }
+                };
+                if old_version > 0 && old.version != old_version {
+                    return Err(err_code_box(
+                        VERSION_ERR,
+                        format!(
+                            "the document:{} version not right expected:{} found:{}",
+                            doc.id, old_version, old.version
+                        ),
+                    ));
+                }
+                merge_doc(&mut doc, old)?;
+                doc.version += old_version + 1;
+                staged.insert(iid.clone(), Some(doc.clone()));
+                let mut ops = self._chunk_source(&mut doc)?;
+                let mut buf = Vec::new();
+                if let Err(error) = doc.encode(&mut buf) {
+                    return Err(error.into());
+                }
+                ops.push(BatchOp::Put(iid, buf));
+                Ok(ops)
+            }
+            Some(WriteType::Upsert) => {
+                let iid = doc_id(&doc);
+                let old = match staged.get(&iid) {
+                    Some(Some(staged_doc)) => Some(staged_doc.clone()),
+                    Some(None) => None,
+                    //`get`, not `get_by_iid`: a previously-chunked document's raw value is
+                    //just its chunk-hash-list marker, which `merge_doc` can't parse as JSON
+                    None => match self.get(doc.id.as_str(), doc.sort_key.as_str()) {
+                        Ok(o) => Some(Message::decode(prost::bytes::Bytes::from(o))?),
+                        Err(e) => {
+                            let e = cast_to_err(e);
+                            if e.0 == NOT_FOUND {
+                                None
+                            } else {
+                                return Err(e);
+                            }
+                        }
+                    },
+                };
+
+                if let Some(old) = old {
+                    doc.version = old.version + 1;
+                    merge_doc(&mut doc, old)?;
+                } else {
+                    doc.version = 1;
+                }
+
+                staged.insert(iid.clone(), Some(doc.clone()));
+                let mut ops = self._chunk_source(&mut doc)?;
+                let mut buf = Vec::new();
+                if let Err(error) = doc.encode(&mut buf) {
+                    return Err(error.into());
+                }
+                ops.push(BatchOp::Put(iid, buf));
+                Ok(ops)
+            }
+            Some(_) | None => Err(err_box(format!("can not do the handler:{:?}", write_type))),
+        }
+    }
+
     fn _create(&self, mut doc: Document) -> ASResult<()> {
         let iid = doc_id(&doc);
         doc.version = 1;
+        let mut ops = self._chunk_source(&mut doc)?;
         let mut buf1 = Vec::new();
         if let Err(error) = doc.encode(&mut buf1) {
             return Err(error.into());
@@ -192,7 +465,8 @@ impl Simba {
             return Err(err_box(format!("the document:{:?} already exists", iid)));
         }
 
-        self.do_write(&iid, &buf1)
+        ops.push(BatchOp::Put(iid, buf1));
+        self.do_batch_write(ops)
     }
 
     fn _update(&self, mut doc: Document) -> ASResult<()> {
@@ -212,18 +486,102 @@ impl Simba {
         }
         merge_doc(&mut doc, old)?;
         doc.version += old_version + 1;
+        let mut ops = self._chunk_source(&mut doc)?;
         let mut buf1 = Vec::new();
         if let Err(error) = doc.encode(&mut buf1) {
             return Err(error.into());
         }
 
-        self.do_write(&iid, &buf1)
+        ops.push(BatchOp::Put(iid, buf1));
+        self.do_batch_write(ops)
+    }
+
+    //fetches a document together with its causality token, so a client can do a proper
+    //read-modify-write across replicas instead of relying on the single `version` counter
+    pub fn get_with_token(&self, id: &str, sort_key: &str) -> ASResult<(Vec<u8>, CausalityToken)> {
+        let iid = id_coding(id, sort_key);
+        let doc = self.get(id, sort_key)?;
+        let token = self._read_token(iid.as_ref());
+        Ok((doc, token))
+    }
+
+    fn _read_token(&self, iid: &[u8]) -> CausalityToken {
+        match self.get_by_iid(&causality_key(iid)) {
+            Ok(bytes) => decode_token(&bytes),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    //`WriteType::Update` variant that takes the causality token the client read back from
+    //`get_with_token`. A write whose token is strictly dominated by the stored token is
+    //rejected with `CAUSALITY_CONFLICT`; a genuine conflict (neither side dominates) keeps both
+    //branches' `source` as siblings for the client to reconcile instead of picking one
+    pub fn update_with_token(
+        &self,
+        mut doc: Document,
+        token: CausalityToken,
+    ) -> ASResult<CausalityToken> {
+        let iid = doc_id(&doc);
+        let _lock = self.latch.latch_lock(doc.slot);
+
+        let stored_token = self._read_token(iid.as_ref());
+
+        match compare(&token, &stored_token) {
+            CausalityOrdering::Dominated => {
+                return Err(err_code_box(
+                    CAUSALITY_CONFLICT,
+                    format!(
+                        "the document:{} was updated concurrently, re-read and retry",
+                        doc.id
+                    ),
+                ));
+            }
+            CausalityOrdering::Conflict => {
+                let old = self.get(doc.id.as_str(), doc.sort_key.as_str())?;
+                let old: Document = Message::decode(prost::bytes::Bytes::from(old))?;
+                merge_doc_siblings(&mut doc, old)?;
+            }
+            CausalityOrdering::Equal | CausalityOrdering::Dominates => {
+                //an empty stored token only means no one has called `update_with_token` on
+                //this id before -- it says nothing about whether the document exists, since
+                //`_create`/`_overwrite`/`_upsert`/`batch_write` never write a `ct/` entry.
+                //Check the document itself rather than branching on the token
+                match self.get(doc.id.as_str(), doc.sort_key.as_str()) {
+                    Ok(old) => {
+                        let old: Document = Message::decode(prost::bytes::Bytes::from(old))?;
+                        //`merge_doc` already sets `doc.version = old.version + 1`
+                        merge_doc(&mut doc, old)?;
+                    }
+                    Err(e) => {
+                        let e = cast_to_err(e);
+                        if e.0 != NOT_FOUND {
+                            return Err(e);
+                        }
+                        doc.version = 1;
+                    }
+                }
+            }
+        }
+
+        let mut ops = self._chunk_source(&mut doc)?;
+        let mut buf = Vec::new();
+        if let Err(error) = doc.encode(&mut buf) {
+            return Err(error.into());
+        }
+
+        //the document, its chunk payloads and its new causality token are all committed
+        //through the same raft round so none of them can be ahead of (or behind) the others
+        let new_token = advance(&merge_tokens(&token, &stored_token), self.server_id);
+        ops.push(BatchOp::Put(iid.clone(), buf));
+        ops.push(BatchOp::Aux(causality_key(iid.as_ref()), encode_token(&new_token)));
+        self.do_batch_write(ops)?;
+        Ok(new_token)
     }
 
     fn _upsert(&self, mut doc: Document) -> ASResult<()> {
         let iid = doc_id(&doc);
         let _lock = self.latch.latch_lock(doc.slot);
-        let old = match self.get_by_iid(iid.as_ref()) {
+        let old = match self.get(doc.id.as_str(), doc.sort_key.as_str()) {
             Ok(o) => Some(o),
             Err(e) => {
                 let e = cast_to_err(e);
@@ -243,11 +601,13 @@ impl Simba {
             doc.version = 1;
         }
 
+        let mut ops = self._chunk_source(&mut doc)?;
         let mut buf1 = Vec::new();
         if let Err(error) = doc.encode(&mut buf1) {
             return Err(error.into());
         }
-        self.do_write(&iid, &buf1)
+        ops.push(BatchOp::Put(iid, buf1));
+        self.do_batch_write(ops)
     }
 
     fn _delete(&self, doc: Document) -> ASResult<()> {
@@ -260,52 +620,70 @@ impl Simba {
         let iid = doc_id(&doc);
         let mut buf1 = Vec::new();
         doc.version = 1;
+        let mut ops = self._chunk_source(&mut doc)?;
         if let Err(error) = doc.encode(&mut buf1) {
             return Err(error.into());
         }
         let _lock = self.latch.latch_lock(doc.slot);
-        self.do_write(&iid, &buf1)
-    }
-
-     fn do_write(&self, key: &Vec<u8>, value: &Vec<u8>) -> ASResult<()> {
-        if self.check_writable() {
-        self.rocksdb.write(key, value)?;
-        self.tantivy.write(key, value)?;
-            let latch = Arc::new(CountDownLatch::new(1));
-            self.raft.as_ref().unwrap().append(
-                PutEvent {
-                    k: key.to_vec(),
-                    v: value.to_vec(),
-                },
-                WriteRaftCallback {
-                    latch: latch.clone(),
-                },
-            );
-            latch.wait();
-            Ok(())
-        } else {
-            Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!"))
+        ops.push(BatchOp::Put(iid, buf1));
+        self.do_batch_write(ops)
+    }
+
+    //splits `doc.source` into content-defined chunks once it crosses the configured
+    //threshold, replacing `doc.source` with the ordered list of hashes so `_reassemble_chunks`
+    //can rebuild it, and returns the chunk payloads (that aren't already in RocksDB) as `Aux`
+    //ops for the caller to commit through the same raft round as the document itself -- a
+    //direct local write here would never replicate to followers
+    fn _chunk_source(&self, doc: &mut Document) -> ASResult<Vec<BatchOp>> {
+        if doc.source.len() <= self.chunking.threshold {
+            return Ok(Vec::new());
         }
+
+        let mut hashes = Vec::new();
+        let mut ops = Vec::new();
+        for chunk in chunk_source(&doc.source, &self.chunking) {
+            let hash = chunk_hash(chunk);
+            let key = chunk_key(&hash);
+            if self.get_by_iid(&key).is_err() {
+                ops.push(BatchOp::Aux(key, chunk.to_vec()));
+            }
+            hashes.push(hash);
+        }
+
+        doc.source = encode_chunk_list(&hashes);
+        Ok(ops)
     }
 
-    async fn do_delete(&self, key: &Vec<u8>) -> ASResult<()> {
-        if self.check_writable() {
-        self.rocksdb.delete(key)?;
-        self.tantivy.delete(key)?;
-            let latch = Arc::new(CountDownLatch::new(1));
-            self.raft.as_ref().unwrap().append(
-                DelEvent { k: key.to_vec() },
-                WriteRaftCallback {
-                    latch: latch.clone(),
-                },
-            );
-            latch.wait();
+    //submits to the group-commit pipeline and blocks the caller until the committer task has
+    //appended the coalesced round to raft and applied it to the engines
+    fn do_write(&self, key: &Vec<u8>, value: &Vec<u8>) -> ASResult<()> {
+        if !self.check_writable() {
+            return Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!"));
+        }
+        self.committer
+            .submit(vec![BatchOp::Put(key.clone(), value.clone())])?
+            .recv()
+            .map_err(|_| err_box("committer closed before responding".to_string()))?
+    }
 
-            Ok(())
-        } else {
-            Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!"))
+    fn do_batch_write(&self, ops: Vec<BatchOp>) -> ASResult<()> {
+        if !self.check_writable() {
+            return Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!"));
         }
+        self.committer
+            .submit(ops)?
+            .recv()
+            .map_err(|_| err_box("committer closed before responding".to_string()))?
+    }
 
+    fn do_delete(&self, key: &Vec<u8>) -> ASResult<()> {
+        if !self.check_writable() {
+            return Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!"));
+        }
+        self.committer
+            .submit(vec![BatchOp::Del(key.clone())])?
+            .recv()
+            .map_err(|_| err_box("committer closed before responding".to_string()))?
     }
 
     pub fn readonly(&self) -> bool {
@@ -326,6 +704,174 @@ impl AppendCallback for WriteRaftCallback {
     }
 }
 
+//a single raft entry grouping every put/delete of one committer round (all the `PendingOp`s
+//that `run_committer` coalesced, e.g. one or more `Simba::batch_write` calls), plus any
+//raw auxiliary keys (e.g. causality tokens) that must be committed atomically alongside the
+//documents but aren't themselves indexed by Tantivy
+pub struct BatchEvent {
+    pub puts: Vec<PutEvent>,
+    pub dels: Vec<DelEvent>,
+    pub aux: Vec<PutEvent>,
+}
+
+enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Del(Vec<u8>),
+    //a raw RocksDB-only key/value (e.g. a causality token or a content-addressed chunk
+    //payload) committed through the same raft round as a document write, but never handed
+    //to Tantivy
+    Aux(Vec<u8>, Vec<u8>),
+}
+
+struct PendingOp {
+    ops: Vec<BatchOp>,
+    resp: std::sync::mpsc::Sender<ASResult<()>>,
+}
+
+//the sending half of the group-commit pipeline; `do_write`/`do_delete`/`do_batch_write` hand
+//their ops to it and block on the per-call response channel rather than on their own raft append
+pub struct Committer {
+    tx: tokio::sync::mpsc::UnboundedSender<PendingOp>,
+}
+
+impl Committer {
+    fn submit(&self, ops: Vec<BatchOp>) -> ASResult<std::sync::mpsc::Receiver<ASResult<()>>> {
+        let (resp, resp_rx) = std::sync::mpsc::channel();
+        self.tx
+            .send(PendingOp { ops, resp })
+            .map_err(|_| err_box("committer channel closed".to_string()))?;
+        Ok(resp_rx)
+    }
+}
+
+//coalesces every `PendingOp` arriving within `max_delay_ms` (capped at `max_batch`) into one
+//raft append; only once that round is acknowledged are the puts/deletes applied to the engines,
+//fixing the old do_write/do_delete ordering where engines were mutated ahead of replication
+async fn run_committer(
+    simba: Arc<RwLock<Simba>>,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<PendingOp>,
+    max_batch: usize,
+    max_delay_ms: u64,
+) {
+    loop {
+        let first = match rx.recv().await {
+            Some(p) => p,
+            None => return,
+        };
+        let mut pending = vec![first];
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_millis(max_delay_ms);
+        while pending.len() < max_batch {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(p)) => pending.push(p),
+                Ok(None) => break,
+                Err(_) => break,
+            }
+        }
+
+        let simba = simba.read().unwrap();
+        if !simba.check_writable() {
+            for p in pending {
+                let _ = p
+                    .resp
+                    .send(Err(err_code_str_box(ENGINE_NOT_WRITABLE, "engin not writable!")));
+            }
+            continue;
+        }
+
+        let mut puts = Vec::new();
+        let mut dels = Vec::new();
+        let mut aux = Vec::new();
+        for p in &pending {
+            for op in &p.ops {
+                match op {
+                    BatchOp::Put(k, v) => puts.push(PutEvent {
+                        k: k.clone(),
+                        v: v.clone(),
+                    }),
+                    BatchOp::Del(k) => dels.push(DelEvent { k: k.clone() }),
+                    BatchOp::Aux(k, v) => aux.push(PutEvent {
+                        k: k.clone(),
+                        v: v.clone(),
+                    }),
+                }
+            }
+        }
+
+        let latch = Arc::new(CountDownLatch::new(1));
+        simba.raft.as_ref().unwrap().append(
+            BatchEvent {
+                puts: puts.clone(),
+                dels: dels.clone(),
+                aux: aux.clone(),
+            },
+            WriteRaftCallback {
+                latch: latch.clone(),
+            },
+        );
+        latch.wait();
+
+        //raft has committed the whole round at this point; apply per-`PendingOp` so one
+        //caller's apply failure doesn't get reported back to every other caller whose write
+        //landed fine in the same round
+        for p in pending {
+            let mut applied_ok = true;
+            for op in &p.ops {
+                match op {
+                    BatchOp::Put(k, v) => {
+                        if let Err(e) = simba.rocksdb.as_ref().unwrap().write(k, v) {
+                            error!("apply put has err:{:?}", e);
+                            applied_ok = false;
+                        }
+                        //`v` may hold only the chunk-hash list for a chunked document;
+                        //Tantivy needs the reassembled source or it can never find/index
+                        //the document's real content
+                        let indexed = match simba._reassemble_chunks(v.clone()) {
+                            Ok(full) => full,
+                            Err(e) => {
+                                error!("reassemble chunks for index has err:{:?}", e);
+                                applied_ok = false;
+                                v.clone()
+                            }
+                        };
+                        if let Err(e) = simba.tantivy.write(k, &indexed) {
+                            error!("apply put has err:{:?}", e);
+                            applied_ok = false;
+                        }
+                    }
+                    BatchOp::Del(k) => {
+                        if let Err(e) = simba.rocksdb.as_ref().unwrap().delete(k) {
+                            error!("apply delete has err:{:?}", e);
+                            applied_ok = false;
+                        }
+                        if let Err(e) = simba.tantivy.delete(k) {
+                            error!("apply delete has err:{:?}", e);
+                            applied_ok = false;
+                        }
+                    }
+                    BatchOp::Aux(k, v) => {
+                        if let Err(e) = simba.rocksdb.as_ref().unwrap().write(k, v) {
+                            error!("apply aux put has err:{:?}", e);
+                            applied_ok = false;
+                        }
+                    }
+                }
+            }
+
+            let resp = if applied_ok {
+                Ok(())
+            } else {
+                //distinguish this from "the write never happened": raft already committed and
+                //replicated it, only the local apply to this engine failed and needs reconciling
+                Err(err_box(
+                    "write was committed via raft but failed to apply to the local engine; it will be reconciled on the next replay"
+                        .to_string(),
+                ))
+            };
+            let _ = p.resp.send(resp);
+        }
+    }
+}
+
 impl Simba {
     fn flush(&self) -> ASResult<()> {
         let flush_time = self.conf.ps.flush_sleep_sec.unwrap_or(3) * 1000;
@@ -341,7 +887,7 @@ impl Simba {
 
             let begin = current_millis();
 
-            if let Err(e) = self.rocksdb.flush() {
+            if let Err(e) = self.rocksdb.as_ref().unwrap().flush() {
                 error!("rocksdb flush has err:{:?}", e);
             }
 
@@ -351,7 +897,7 @@ impl Simba {
 
             pre_sn = sn;
 
-            if let Err(e) = self.rocksdb.write_sn(pre_sn) {
+            if let Err(e) = self.rocksdb.as_ref().unwrap().write_sn(pre_sn) {
                 error!("write has err :{:?}", e);
             };
 
@@ -392,7 +938,7 @@ impl Simba {
     }
 
     fn load_engine(&mut self) {
-        let rocksdb = RocksDB::new(BaseEngine::new(&self.base_engine)).unwrap();
+        let rocksdb = open_kv_backend(&self.conf, BaseEngine::new(&self.base_engine)).unwrap();
         let tantivy = Tantivy::new(BaseEngine::new(&self.base_engine)).unwrap();
         let log_start_index = cmp::min(rocksdb.get_sn(), tantivy.get_sn());
         self.rocksdb = Some(rocksdb);
@@ -459,6 +1005,29 @@ impl Simba {
     }
 }
 
+//the immediate successor of `key` in byte-lexicographic order, used to turn an inclusive
+//"last row seen" cursor into the inclusive `start` of the next page without re-returning it
+fn successor_key(key: &[u8]) -> Vec<u8> {
+    let mut next = key.to_vec();
+    next.push(0);
+    next
+}
+
+//smallest key that is strictly greater than every key with `prefix`, i.e. the exclusive
+//upper bound of the [prefix, ..) range; `None` if `prefix` is all 0xFF (the range is
+//unbounded above)
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    for i in (0..end.len()).rev() {
+        if end[i] != 0xFF {
+            end[i] += 1;
+            end.truncate(i + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
 fn merge(a: &mut Value, b: Value) {
     match (a, b) {
         (a @ &mut Value::Object(_), Value::Object(b)) => {
@@ -479,3 +1048,81 @@ fn merge_doc(new: &mut Document, old: Document) -> ASResult<()> {
     new.version = old.version + 1;
     Ok(())
 }
+
+//on a genuine causality conflict (neither the incoming nor the stored token dominates the
+//other), keep both branches' source under `_siblings` instead of silently merging them, so
+//the client can reconcile
+fn merge_doc_siblings(new: &mut Document, old: Document) -> ASResult<()> {
+    let new_src: Value = serde_json::from_slice(new.source.as_slice())?;
+    let old_src: Value = serde_json::from_slice(old.source.as_slice())?;
+    let siblings = serde_json::json!({ "_siblings": [new_src, old_src] });
+    new.source = serde_json::to_vec(&siblings)?;
+    new.version = old.version.max(new.version) + 1;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(source: &str, version: i64) -> Document {
+        Document {
+            source: source.as_bytes().to_vec(),
+            version,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn successor_key_is_strictly_greater_than_its_input() {
+        let key = b"abc".to_vec();
+        let next = successor_key(&key);
+        assert!(next.as_slice() > key.as_slice());
+        assert_eq!(next, b"abc\0".to_vec());
+    }
+
+    #[test]
+    fn prefix_upper_bound_increments_last_non_ff_byte() {
+        assert_eq!(prefix_upper_bound(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_upper_bound(&[0x01, 0xFF]), Some(vec![0x02]));
+    }
+
+    #[test]
+    fn prefix_upper_bound_is_none_when_prefix_is_all_ff() {
+        assert_eq!(prefix_upper_bound(&[0xFF, 0xFF]), None);
+    }
+
+    #[test]
+    fn merge_overwrites_scalars_and_recurses_into_nested_objects() {
+        let mut a = serde_json::json!({"x": 1, "nested": {"a": 1, "b": 1}});
+        let b = serde_json::json!({"x": 2, "nested": {"b": 2, "c": 3}});
+        merge(&mut a, b);
+        assert_eq!(
+            a,
+            serde_json::json!({"x": 2, "nested": {"a": 1, "b": 2, "c": 3}})
+        );
+    }
+
+    #[test]
+    fn merge_doc_combines_sources_and_bumps_version_once() {
+        let mut new = doc(r#"{"b":2}"#, 0);
+        let old = doc(r#"{"a":1,"b":1}"#, 5);
+        merge_doc(&mut new, old).unwrap();
+        let merged: Value = serde_json::from_slice(&new.source).unwrap();
+        assert_eq!(merged, serde_json::json!({"a": 1, "b": 2}));
+        assert_eq!(new.version, 6);
+    }
+
+    #[test]
+    fn merge_doc_siblings_keeps_both_sources_as_siblings() {
+        let mut new = doc(r#"{"a":1}"#, 3);
+        let old = doc(r#"{"a":2}"#, 4);
+        merge_doc_siblings(&mut new, old).unwrap();
+        let merged: Value = serde_json::from_slice(&new.source).unwrap();
+        assert_eq!(
+            merged,
+            serde_json::json!({"_siblings": [{"a": 1}, {"a": 2}]})
+        );
+        assert_eq!(new.version, 5);
+    }
+}